@@ -5,6 +5,12 @@ use core::slice;
 use generic_array::{ArrayLength, GenericArray};
 pub use generic_array::typenum::consts;
 
+#[cfg(feature = "critical-section")]
+mod shared;
+
+#[cfg(feature = "critical-section")]
+pub use shared::SharedTopq;
+
 /// A trait that represents a (probably rolling) timer of arbitrary
 /// precision.
 pub trait Timer {
@@ -22,6 +28,12 @@ pub trait Timer {
     /// be rolling (e.g. when using a `u32` with a 32.768kHz clock), the
     /// addition should be done by wrapping
     fn wrapping_add(time: &Self::Time, offset: &Self::Time) -> Self::Time;
+
+    /// Compute the number of ticks elapsed going from `earlier` to `later`.
+    /// When this type is expected to be rolling, the subtraction should be
+    /// done by wrapping, the same way `wrapping_add` does, so that
+    /// `wrapping_sub(t, wrapping_add(t, offset)) == offset` (as ticks)
+    fn wrapping_sub(earlier: &Self::Time, later: &Self::Time) -> u64;
 }
 
 /// A "Timeout Priority Queue"
@@ -142,6 +154,86 @@ where
         }
     }
 
+    /// Remove the item with the given priority, if any, returning its data
+    ///
+    /// This binary-searches the priority-sorted region the same way
+    /// `insert` does, and compacts the array to fill the gap left behind.
+    /// Use this to retract a previously inserted value before it expires
+    /// naturally, e.g. when a higher-layer condition invalidates it early.
+    pub fn remove(&mut self, prio: &P) -> Option<D> {
+        let start_ptr = self.queue.as_mut_ptr().cast::<TopqItem<D, P, T>>();
+
+        let result_idx = {
+            let slice = unsafe { core::slice::from_raw_parts(start_ptr, self.used) };
+            slice.binary_search_by(|ti| prio.cmp(&ti.prio))
+        };
+
+        let idx = result_idx.ok()?;
+
+        unsafe {
+            let posn = start_ptr.add(idx);
+
+            // Take the data out of the matched slot
+            let removed = core::ptr::read(posn).item;
+
+            // Scootch the tail down by one to close the gap
+            core::ptr::copy(posn.add(1), posn, self.used - idx - 1);
+            self.used -= 1;
+
+            Some(removed)
+        }
+    }
+
+    /// Remove all items for which the given predicate returns `true`
+    ///
+    /// This walks the full used region, compacting the array exactly as
+    /// `prune` does, but lets the caller decide which items to retract
+    /// instead of only acting on expiry.
+    ///
+    /// If `f` panics, `used` is left pointing at whatever prefix had already
+    /// been compacted before the panic, so the queue remains in a
+    /// consistent (if partially processed) state rather than stale over an
+    /// already-mutated array.
+    pub fn remove_if<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&TopqItem<D, P, T>) -> bool,
+    {
+        let start_ptr = self.queue.as_mut_ptr().cast::<TopqItem<D, P, T>>();
+
+        let mut good = 0;
+
+        for idx in 0..self.used {
+            unsafe {
+                let idx_ptr = start_ptr.add(idx);
+
+                let remove_this = f(&*idx_ptr);
+
+                if remove_this {
+                    // This item should go, drop it
+                    core::ptr::drop_in_place(idx_ptr);
+                } else {
+                    // No need to copy if we are already here
+                    if good != idx {
+                        let good_ptr = start_ptr.add(good);
+                        // The destination's old bytes were already logically
+                        // moved out above (dropped if "bad", or relocated to
+                        // an earlier slot if they were "good") -- dropping
+                        // them again here would be a double drop, so just
+                        // overwrite.
+                        core::ptr::copy_nonoverlapping(idx_ptr, good_ptr, 1);
+                    }
+
+                    good += 1;
+                }
+            }
+
+            // Keep `used` in lockstep with every item visited, not just at
+            // the end, so a panic inside `f` can't leave `used` pointing at
+            // stale state over an array that has already been mutated.
+            self.used = good;
+        }
+    }
+
     /// Remove any expired items from the priority queue
     ///
     /// See the module level documentation for when it is necessary to call this function
@@ -163,10 +255,11 @@ where
                 if idx_good {
                     // No need to copy if we are already here
                     if good != idx {
-                        // Drop the destination item
-                        core::ptr::drop_in_place(good_ptr);
-
-                        // Move from source to destination
+                        // The destination's old bytes were already logically
+                        // moved out above (dropped if "bad", or relocated to
+                        // an earlier slot if they were "good") -- dropping
+                        // them again here would be a double drop, so just
+                        // overwrite.
                         core::ptr::copy_nonoverlapping(idx_ptr, good_ptr, 1);
                     }
 
@@ -182,6 +275,31 @@ where
         self.used = good;
     }
 
+    /// Remove expired items from the priority queue, yielding the data of each one
+    ///
+    /// Unlike `prune`, which silently discards expired entries, this returns a
+    /// lazy iterator that hands ownership of each expired item's data back to
+    /// the caller as it is encountered, so it can be logged, recycled, or
+    /// otherwise observed before being dropped.
+    ///
+    /// The backing array is compacted exactly as `prune` does. If the
+    /// iterator is dropped before being fully consumed, the remaining
+    /// compaction is finished by its `Drop` implementation, so `used` is
+    /// never left inconsistent with the (possibly uninitialized) backing
+    /// storage.
+    pub fn drain_expired(&mut self) -> DrainExpired<'_, D, P, T, N> {
+        let now = self.timer.now();
+        let total = self.used;
+
+        DrainExpired {
+            topq: self,
+            now,
+            idx: 0,
+            good: 0,
+            total,
+        }
+    }
+
     /// Obtain the highest priority and currently valid data, if any
     ///
     /// This is typically used when you ONLY need the current value, and not
@@ -195,11 +313,153 @@ where
     /// This is typically used when you need the current value, AND ALSO need
     /// the remaining validity time or the priority of the currently valid data
     pub fn get_item(&self) -> Option<&TopqItem<D, P, T>> {
+        let now = self.timer.now();
+        self.get_item_at(&now)
+    }
+
+    /// Obtain the highest priority and currently valid topq item as of `now`
+    ///
+    /// This is split out from `get_item` so that callers needing a second
+    /// derived value (e.g. `remaining`) can reuse a single `now` snapshot
+    /// instead of racing two separate `Timer::now()` calls against each
+    /// other around the exact expiry tick.
+    fn get_item_at(&self, now: &T::Time) -> Option<&TopqItem<D, P, T>> {
         let start_ptr = self.queue.as_ptr().cast::<TopqItem<D, P, T>>();
         let slice = unsafe { core::slice::from_raw_parts(start_ptr, self.used) };
 
+        slice.iter().find(|item| item.valid_at_time(now))
+    }
+
+    /// Obtain the earliest time at which the result of `get_data()` could change
+    ///
+    /// This is the `expiry_time` of the currently-active (highest priority,
+    /// currently valid) item, since that is the next moment at which it could
+    /// stop being valid and a lower priority (or no) item would take its place.
+    /// A caller can use this to program a hardware timer/alarm to wake exactly
+    /// then, rather than polling `get_data`/`get_item` on a fixed interval.
+    ///
+    /// Returns `None` if the queue currently holds no valid item, in which
+    /// case there is nothing to wait on until a new item is inserted.
+    pub fn next_transition_time(&self) -> Option<T::Time>
+    where
+        T::Time: Clone,
+    {
+        self.get_item().map(|item| item.expiry_time.clone())
+    }
+
+    /// Report the ticks of validity remaining on the currently active item
+    ///
+    /// This finds the currently active item the same way `get_item` does and
+    /// applies `TopqItem::time_remaining` to it, using a single `now` reading
+    /// for both steps, for callers that only care about freshness, not the
+    /// full item. Returns `None` if there is no currently valid item.
+    pub fn remaining(&self) -> Option<u64> {
         let now = self.timer.now();
-        slice.iter().find(|item| item.valid_at_time(&now))
+        self.get_item_at(&now).and_then(|item| item.time_remaining(&now))
+    }
+}
+
+/// An iterator, created by [`Topq::drain_expired`], that removes expired
+/// items from a `Topq` and yields the data of each one
+///
+/// The backing array is compacted as the iterator progresses, in the same
+/// manner as `prune`. Dropping the iterator before exhausting it still
+/// finishes the compaction, so the queue is left in a consistent state
+/// regardless of how many items were actually consumed.
+pub struct DrainExpired<'a, D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    topq: &'a mut Topq<D, P, T, N>,
+    now: T::Time,
+    idx: usize,
+    good: usize,
+    total: usize,
+}
+
+impl<D, P, T, N> DrainExpired<'_, D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    /// Advance past the item at `idx`, compacting it down to `good` if it is
+    /// still valid, or taking ownership of its data if it has expired
+    fn step(&mut self) -> Option<D> {
+        let start_ptr = self.topq.queue.as_mut_ptr().cast::<TopqItem<D, P, T>>();
+
+        let idx_ptr = unsafe { start_ptr.add(self.idx) };
+        let idx_good = unsafe { (*idx_ptr).valid_at_time(&self.now) };
+
+        let out = if idx_good {
+            if self.good != self.idx {
+                let good_ptr = unsafe { start_ptr.add(self.good) };
+                // The destination's old bytes were already logically moved
+                // out by an earlier step (dropped if expired, or relocated
+                // to an even earlier slot if they were retained) -- dropping
+                // them again here would be a double drop, so just overwrite.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(idx_ptr, good_ptr, 1);
+                }
+            }
+
+            self.good += 1;
+            None
+        } else {
+            Some(unsafe { core::ptr::read(idx_ptr) }.item)
+        };
+
+        self.idx += 1;
+
+        // Keep `topq.used` in lockstep with every step, not just when the
+        // iterator is dropped, so a caller that leaks this iterator (e.g.
+        // via `mem::forget`) can't resurrect stale or already-moved-from
+        // slots -- the queue is left pointing at whatever compacted prefix
+        // had actually been confirmed, same as `Vec::drain`.
+        self.topq.used = self.good;
+
+        out
+    }
+}
+
+impl<D, P, T, N> Iterator for DrainExpired<'_, D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    type Item = D;
+
+    fn next(&mut self) -> Option<D> {
+        while self.idx < self.total {
+            if let Some(item) = self.step() {
+                return Some(item);
+            }
+        }
+
+        None
+    }
+}
+
+impl<D, P, T, N> Drop for DrainExpired<'_, D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    fn drop(&mut self) {
+        // Finish compacting any items that were never visited by `next`.
+        // `step` keeps `topq.used` current as it goes, so nothing further
+        // needs to be done here once the loop completes.
+        while self.idx < self.total {
+            self.step();
+        }
     }
 }
 
@@ -231,6 +491,34 @@ where
             *time >= self.start_time || *time <= self.expiry_time
         }
     }
+
+    /// Compute the number of ticks remaining until this item expires, as of `now`
+    ///
+    /// Returns `None` if the item is not valid at `now`, e.g. because it has
+    /// already expired. The result is in raw ticks of `T`; see
+    /// [`TopqItem::remaining_millis`] and [`TopqItem::remaining_secs`] for the
+    /// same value converted using `T::TICKS_PER_SECOND`.
+    pub fn time_remaining(&self, now: &T::Time) -> Option<u64> {
+        if self.valid_at_time(now) {
+            Some(T::wrapping_sub(now, &self.expiry_time))
+        } else {
+            None
+        }
+    }
+
+    /// Like [`TopqItem::time_remaining`], but converted to whole milliseconds
+    /// using `T::TICKS_PER_SECOND`
+    pub fn remaining_millis(&self, now: &T::Time) -> Option<u64> {
+        self.time_remaining(now)
+            .map(|ticks| ticks.saturating_mul(1000) / u64::from(T::TICKS_PER_SECOND))
+    }
+
+    /// Like [`TopqItem::time_remaining`], but converted to whole seconds
+    /// using `T::TICKS_PER_SECOND`
+    pub fn remaining_secs(&self, now: &T::Time) -> Option<u64> {
+        self.time_remaining(now)
+            .map(|ticks| ticks / u64::from(T::TICKS_PER_SECOND))
+    }
 }
 
 impl<'a, D, P, T, N> IntoIterator for &'a Topq<D, P, T, N>
@@ -287,6 +575,10 @@ mod test {
         fn wrapping_add(a: &u32, b: &u32) -> u32 {
             a.wrapping_add(*b)
         }
+
+        fn wrapping_sub(earlier: &u32, later: &u32) -> u64 {
+            u64::from(later.wrapping_sub(*earlier))
+        }
     }
 
     #[derive(Debug, PartialOrd, Ord, Eq, PartialEq)]
@@ -434,4 +726,395 @@ mod test {
         TIMER.store(0x0000_0011, SeqCst);
         assert_eq!(q.get_data(), None);
     }
+
+    #[test]
+    fn prune_does_not_double_drop_retained_items() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct DropCounter(Rc<Cell<u32>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<DropCounter, u8, FakeTimer, U4> = Topq::new(timer);
+
+        let counter = Rc::new(Cell::new(0));
+
+        // Higher priority, shorter-lived item sits ahead of the lower
+        // priority, longer-lived one in the backing array.
+        q.insert(DropCounter(counter.clone()), 5, 10);
+        q.insert(DropCounter(counter.clone()), 3, 30);
+
+        TIMER.store(11, SeqCst);
+
+        // Pruning the expired priority-5 item forces the retained
+        // priority-3 item to be compacted over its now-vacated slot.
+        q.prune();
+        assert_eq!(counter.get(), 1);
+
+        // Flush the remaining retained item (`Topq` has no `Drop` impl of
+        // its own, so it must be removed explicitly to observe this)
+        q.remove_if(|_| true);
+        assert_eq!(counter.get(), 2);
+    }
+
+    #[test]
+    fn next_transition() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        assert_eq!(q.next_transition_time(), None);
+
+        q.insert(10, 3, 30);
+        q.insert(12, 5, 20);
+        assert_eq!(q.next_transition_time(), Some(20));
+
+        TIMER.store(21, SeqCst);
+        assert_eq!(q.next_transition_time(), Some(30));
+
+        TIMER.store(31, SeqCst);
+        assert_eq!(q.next_transition_time(), None);
+    }
+
+    #[test]
+    fn next_transition_rollover() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        TIMER.store(0xFFFF_FFF0, SeqCst);
+        q.insert(10, 3, 32);
+        assert_eq!(q.next_transition_time(), Some(0x0000_0010));
+
+        TIMER.store(0x0000_0000, SeqCst);
+        assert_eq!(q.next_transition_time(), Some(0x0000_0010));
+    }
+
+    #[test]
+    fn drain_expired_yields_expired_data() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 30);
+        q.insert(11, 4, 25);
+        q.insert(12, 5, 20);
+        q.insert(13, 6, 15);
+
+        TIMER.store(21, SeqCst);
+
+        let mut drained: Vec<u32> = q.drain_expired().collect();
+        drained.sort_unstable();
+        assert_eq!(drained, vec![12, 13]);
+
+        assert_eq!(q.get_data(), Some(&11));
+
+        q.into_iter().for_each(|t| {
+            println!("{:?}", t);
+        });
+    }
+
+    #[test]
+    fn drain_expired_partial_consumption_still_compacts() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 30);
+        q.insert(11, 4, 25);
+        q.insert(12, 5, 20);
+        q.insert(13, 6, 15);
+
+        TIMER.store(21, SeqCst);
+
+        // Only take the first expired item, then drop the iterator early
+        let first = q.drain_expired().next();
+        assert!(first == Some(12) || first == Some(13));
+
+        // The queue must still be left in a consistent state
+        assert_eq!(q.get_data(), Some(&11));
+
+        TIMER.store(26, SeqCst);
+        assert_eq!(q.get_data(), Some(&10));
+    }
+
+    #[test]
+    fn drain_expired_does_not_double_drop_retained_items() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct DropCounter(Rc<Cell<u32>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<DropCounter, u8, FakeTimer, U4> = Topq::new(timer);
+
+        let counter = Rc::new(Cell::new(0));
+
+        q.insert(DropCounter(counter.clone()), 3, 30);
+        q.insert(DropCounter(counter.clone()), 4, 25);
+        q.insert(DropCounter(counter.clone()), 5, 20);
+        q.insert(DropCounter(counter.clone()), 6, 15);
+
+        TIMER.store(21, SeqCst);
+
+        // Priorities 5 and 6 have expired; draining them forces the
+        // retained priority 3 and 4 items to be compacted over the
+        // now-vacated slots.
+        let drained: Vec<DropCounter> = q.drain_expired().collect();
+        assert_eq!(drained.len(), 2);
+
+        // The drained items haven't been dropped yet, and compacting the
+        // retained ones over their old slots must not have dropped them
+        assert_eq!(counter.get(), 0);
+        drop(drained);
+        assert_eq!(counter.get(), 2);
+
+        // Flush the remaining two retained items (`Topq` has no `Drop` impl
+        // of its own, so they must be removed explicitly to observe this)
+        q.remove_if(|_| true);
+        assert_eq!(counter.get(), 4);
+    }
+
+    #[test]
+    fn drain_expired_forgotten_iterator_leaks_safely() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct DropCounter(Rc<Cell<u32>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<DropCounter, u8, FakeTimer, U4> = Topq::new(timer);
+
+        let counter = Rc::new(Cell::new(0));
+
+        q.insert(DropCounter(counter.clone()), 3, 30);
+        q.insert(DropCounter(counter.clone()), 4, 25);
+        q.insert(DropCounter(counter.clone()), 5, 20);
+        q.insert(DropCounter(counter.clone()), 6, 15);
+
+        TIMER.store(21, SeqCst);
+
+        // Consume exactly one expired item, then leak the iterator before
+        // it finishes compacting the rest of the array
+        let mut drain = q.drain_expired();
+        let first = drain.next();
+        assert!(first.is_some());
+        core::mem::forget(drain);
+
+        // Nothing is dropped while the consumed item is still held; dropping
+        // it accounts for exactly one drop
+        assert_eq!(counter.get(), 0);
+        drop(first);
+        assert_eq!(counter.get(), 1);
+
+        // `used` must have been kept in lockstep as the iterator stepped,
+        // rather than only updated on `Drop`, so the remaining items are
+        // simply (and safely) leaked -- not double-dropped or read out of
+        // already-moved-from memory -- when the iterator is forgotten
+        // instead of run to completion.
+        q.prune();
+        assert_eq!(counter.get(), 1);
+    }
+
+    #[test]
+    fn remove_by_priority() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 30);
+        q.insert(11, 4, 25);
+        q.insert(12, 5, 20);
+        assert_eq!(q.get_data(), Some(&12));
+
+        // Removing a priority that isn't present is a no-op
+        assert_eq!(q.remove(&9), None);
+
+        // Cancel the currently-active item, the next highest takes over
+        assert_eq!(q.remove(&5), Some(12));
+        assert_eq!(q.get_data(), Some(&11));
+
+        // Cancelling again has no effect, and the array stays consistent
+        assert_eq!(q.remove(&5), None);
+        assert_eq!(q.remove(&4), Some(11));
+        assert_eq!(q.get_data(), Some(&10));
+        assert_eq!(q.remove(&3), Some(10));
+        assert_eq!(q.get_data(), None);
+    }
+
+    #[test]
+    fn remove_if_predicate() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 30);
+        q.insert(11, 4, 25);
+        q.insert(12, 5, 20);
+        q.insert(13, 6, 15);
+
+        // Drop every item with an odd priority, leaving 11 (prio 4) and 13 (prio 6)
+        q.remove_if(|item| item.prio % 2 == 1);
+
+        assert_eq!(q.get_data(), Some(&13));
+        q.remove_if(|item| item.prio == 6);
+        assert_eq!(q.get_data(), Some(&11));
+    }
+
+    #[test]
+    fn remove_if_does_not_double_drop_retained_items() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct DropCounter(Rc<Cell<u32>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<DropCounter, u8, FakeTimer, U4> = Topq::new(timer);
+
+        let counter = Rc::new(Cell::new(0));
+
+        q.insert(DropCounter(counter.clone()), 3, 30);
+        q.insert(DropCounter(counter.clone()), 4, 25);
+        q.insert(DropCounter(counter.clone()), 5, 20);
+        q.insert(DropCounter(counter.clone()), 6, 15);
+
+        // Removing priorities 5 and 6 forces the retained priority 3 and 4
+        // items to be compacted down over the now-vacated slots.
+        q.remove_if(|item| item.prio >= 5);
+
+        // Exactly the two removed items should have been dropped so far --
+        // compacting the retained items over their old slots must not
+        // double-drop them.
+        assert_eq!(counter.get(), 2);
+
+        // Flush the remaining two retained items to confirm nothing was
+        // silently leaked or double-counted either
+        q.remove_if(|_| true);
+        assert_eq!(counter.get(), 4);
+    }
+
+    #[test]
+    fn time_remaining() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let mut q: Topq<u32, u8, FakeTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 30);
+        assert_eq!(q.remaining(), Some(30));
+
+        TIMER.store(10, SeqCst);
+        assert_eq!(q.remaining(), Some(20));
+
+        TIMER.store(30, SeqCst);
+        assert_eq!(q.remaining(), Some(0));
+
+        TIMER.store(31, SeqCst);
+        assert_eq!(q.remaining(), None);
+    }
+
+    #[test]
+    fn remaining_uses_a_single_now_snapshot() {
+        use std::cell::Cell;
+
+        // A timer that advances by one tick on every call to `now`, to
+        // simulate a live clock ticking over between two separate `now()`
+        // calls made within the same logical operation.
+        struct SteppingTimer(Cell<u32>);
+
+        impl Timer for SteppingTimer {
+            type Time = u32;
+            const TICKS_PER_SECOND: u32 = 1;
+
+            fn now(&self) -> u32 {
+                let now = self.0.get();
+                self.0.set(now.wrapping_add(1));
+                now
+            }
+
+            fn wrapping_add(a: &u32, b: &u32) -> u32 {
+                a.wrapping_add(*b)
+            }
+
+            fn wrapping_sub(earlier: &u32, later: &u32) -> u64 {
+                u64::from(later.wrapping_sub(*earlier))
+            }
+        }
+
+        let timer = SteppingTimer(Cell::new(0));
+        let mut q: Topq<u32, u8, SteppingTimer, U4> = Topq::new(timer);
+
+        // Inserting consumes the tick-0 `now()` reading, giving the item a
+        // validity window of exactly [0, 1].
+        q.insert(10, 3, 1);
+
+        // `remaining` must use exactly one `now()` reading for both locating
+        // the active item and computing its remainder. A two-reading
+        // implementation would see the item as valid via the first reading
+        // (tick 1, still inside [0, 1]) but already expired by the second
+        // (tick 2), spuriously returning `None`.
+        assert_eq!(q.remaining(), Some(0));
+    }
+
+    #[test]
+    fn remaining_millis_and_secs() {
+        struct KhzTimer(&'static AtomicU32);
+
+        impl Timer for KhzTimer {
+            type Time = u32;
+            const TICKS_PER_SECOND: u32 = 1_000;
+
+            fn now(&self) -> u32 {
+                self.0.load(SeqCst)
+            }
+
+            fn wrapping_add(a: &u32, b: &u32) -> u32 {
+                a.wrapping_add(*b)
+            }
+
+            fn wrapping_sub(earlier: &u32, later: &u32) -> u64 {
+                u64::from(later.wrapping_sub(*earlier))
+            }
+        }
+
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = KhzTimer(&TIMER);
+        let mut q: Topq<u32, u8, KhzTimer, U4> = Topq::new(timer);
+
+        q.insert(10, 3, 5_000);
+
+        let item = q.get_item().unwrap();
+        assert_eq!(item.remaining_millis(&0), Some(5_000));
+        assert_eq!(item.remaining_secs(&0), Some(5));
+
+        assert_eq!(item.remaining_millis(&2_500), Some(2_500));
+        assert_eq!(item.remaining_secs(&2_500), Some(2));
+    }
 }
@@ -0,0 +1,215 @@
+//! An interrupt-safe wrapper around [`Topq`] for splitting producer and
+//! consumer across an ISR and the main loop.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use generic_array::ArrayLength;
+
+use crate::{Timer, Topq, TopqItem};
+
+/// A `Topq` wrapped for safe sharing between an interrupt handler and the
+/// main loop
+///
+/// `Topq` requires `&mut self` for every mutating operation and performs raw
+/// `MaybeUninit` pointer writes internally, which are not sound to
+/// interleave across execution contexts. `SharedTopq` instead owns the queue
+/// inside a `critical_section::Mutex<RefCell<..>>`, so each operation only
+/// enters a critical section for the duration of its array mutation. This is
+/// the common embedded pattern of an ISR posting sensor readings via
+/// `insert` while the main loop reads the current best value via
+/// `get_data`, without hand-rolling `unsafe`.
+///
+/// This type is only available with the `critical-section` feature enabled,
+/// so users who don't need cross-context sharing pay nothing for it.
+pub struct SharedTopq<D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    inner: Mutex<RefCell<Topq<D, P, T, N>>>,
+}
+
+impl<D, P, T, N> SharedTopq<D, P, T, N>
+where
+    D: 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    /// Create an empty `SharedTopq` with the given timer
+    pub fn new(timer: T) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Topq::new(timer))),
+        }
+    }
+
+    /// Insert a datapoint into the priority queue
+    ///
+    /// See [`Topq::insert`] for the replacement/eviction rules. This enters
+    /// a critical section for the duration of the insertion.
+    pub fn insert(&self, item: D, prio: P, valid_for: T::Time) {
+        critical_section::with(|cs| {
+            self.inner.borrow_ref_mut(cs).insert(item, prio, valid_for);
+        });
+    }
+
+    /// Remove any expired items from the priority queue
+    ///
+    /// See the [`Topq`] module level documentation for when it is necessary
+    /// to call this function. This enters a critical section for the
+    /// duration of the prune.
+    pub fn prune(&self) {
+        critical_section::with(|cs| {
+            self.inner.borrow_ref_mut(cs).prune();
+        });
+    }
+}
+
+impl<D, P, T, N> SharedTopq<D, P, T, N>
+where
+    D: Copy + 'static,
+    P: Ord,
+    T: Timer,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    /// Obtain a copy of the highest priority and currently valid data, if any
+    ///
+    /// This is typically used when you ONLY need the current value, and not
+    /// the remaining validity time or the priority of the currently valid
+    /// data. Requires `D: Copy` since the value cannot be borrowed out past
+    /// the end of the critical section.
+    pub fn get_data(&self) -> Option<D> {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).get_data().copied())
+    }
+}
+
+impl<D, P, T, N> SharedTopq<D, P, T, N>
+where
+    D: Copy + 'static,
+    P: Ord + Copy,
+    T: Timer,
+    T::Time: Copy,
+    N: ArrayLength<TopqItem<D, P, T>>,
+{
+    /// Obtain a copy of the highest priority and currently valid topq item, if any
+    ///
+    /// This is typically used when you need the current value, AND ALSO need
+    /// the remaining validity time or the priority of the currently valid
+    /// data. Requires `D`, `P`, and `T::Time` to be `Copy` since the item
+    /// cannot be borrowed out past the end of the critical section.
+    pub fn get_item_copied(&self) -> Option<TopqItem<D, P, T>> {
+        critical_section::with(|cs| {
+            self.inner.borrow_ref(cs).get_item().map(|item| TopqItem {
+                item: item.item,
+                prio: item.prio,
+                start_time: item.start_time,
+                expiry_time: item.expiry_time,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, Ordering::SeqCst};
+    use generic_array::typenum::consts::U4;
+
+    #[derive(Debug)]
+    struct FakeTimer(&'static AtomicU32);
+
+    impl Timer for FakeTimer {
+        type Time = u32;
+        const TICKS_PER_SECOND: u32 = 1;
+
+        fn now(&self) -> u32 {
+            self.0.load(SeqCst)
+        }
+
+        fn wrapping_add(a: &u32, b: &u32) -> u32 {
+            a.wrapping_add(*b)
+        }
+
+        fn wrapping_sub(earlier: &u32, later: &u32) -> u64 {
+            u64::from(later.wrapping_sub(*earlier))
+        }
+    }
+
+    #[test]
+    fn insert_get_data_prune_round_trip() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let q: SharedTopq<u32, u8, FakeTimer, U4> = SharedTopq::new(timer);
+
+        assert_eq!(q.get_data(), None);
+
+        q.insert(10, 3, 30);
+        assert_eq!(q.get_data(), Some(10));
+
+        q.insert(11, 4, 25);
+        assert_eq!(q.get_data(), Some(11));
+
+        let item = q.get_item_copied().unwrap();
+        assert_eq!(item.item, 11);
+        assert_eq!(item.prio, 4);
+
+        TIMER.store(31, SeqCst);
+        assert_eq!(q.get_data(), None);
+
+        // Expired, but still occupying a slot until pruned
+        q.prune();
+        assert_eq!(q.get_data(), None);
+    }
+
+    #[test]
+    fn replaces_existing_priority() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let q: SharedTopq<u32, u8, FakeTimer, U4> = SharedTopq::new(timer);
+
+        q.insert(10, 5, 30);
+        assert_eq!(q.get_data(), Some(10));
+
+        // Re-inserting at the same priority replaces the old data/timeout
+        // rather than adding a second entry
+        q.insert(11, 5, 10);
+        assert_eq!(q.get_data(), Some(11));
+
+        let item = q.get_item_copied().unwrap();
+        assert_eq!(item.item, 11);
+        assert_eq!(item.prio, 5);
+
+        TIMER.store(11, SeqCst);
+        assert_eq!(q.get_data(), None);
+    }
+
+    #[test]
+    fn rollover_via_get_item_copied() {
+        static TIMER: AtomicU32 = AtomicU32::new(0);
+        let timer = FakeTimer(&TIMER);
+        let q: SharedTopq<u32, u8, FakeTimer, U4> = SharedTopq::new(timer);
+
+        TIMER.store(0xFFFF_FFF0, SeqCst);
+        q.insert(10, 3, 32);
+
+        let item = q.get_item_copied().unwrap();
+        assert_eq!(item.item, 10);
+        assert_eq!(item.start_time, 0xFFFF_FFF0);
+        assert_eq!(item.expiry_time, 0x0000_0010);
+
+        TIMER.store(0xFFFF_FFFF, SeqCst);
+        assert_eq!(q.get_item_copied().map(|i| i.item), Some(10));
+
+        TIMER.store(0x0000_0000, SeqCst);
+        assert_eq!(q.get_item_copied().map(|i| i.item), Some(10));
+
+        TIMER.store(0x0000_0010, SeqCst);
+        assert_eq!(q.get_item_copied().map(|i| i.item), Some(10));
+
+        TIMER.store(0x0000_0011, SeqCst);
+        assert!(q.get_item_copied().is_none());
+    }
+}